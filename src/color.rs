@@ -0,0 +1,76 @@
+//! ANSI syntax highlighting of diagnostic notation, à la the `hx` hex viewer.
+//!
+//! Each token class gets its own colour so that large nested structures are
+//! easier to scan. Whether colour is emitted is decided by [`should_color`],
+//! honouring `--color` and the `NO_COLOR` environment variable.
+
+use std::io::IsTerminal;
+
+use crate::token::{tokenize, TokenKind};
+
+/// When to emit ANSI colour codes, mirroring the familiar `auto|always|never`
+/// tri-state used by many command-line tools.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Colourize only when standard output is a terminal.
+    Auto,
+    /// Always colourize.
+    Always,
+    /// Never colourize.
+    Never,
+}
+
+/// Resolve a [`ColorChoice`] against the environment. `NO_COLOR` suppresses
+/// colour regardless of the choice; otherwise `Auto` colourizes only when
+/// stdout is a TTY.
+pub fn should_color(choice: ColorChoice) -> bool {
+    if std::env::var_os("NO_COLOR").is_some() {
+        return false;
+    }
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => std::io::stdout().is_terminal(),
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+
+fn ansi(kind: TokenKind, is_tag: bool) -> Option<&'static str> {
+    if is_tag {
+        return Some("\x1b[1;31m"); // bold red for tag numbers
+    }
+    let code = match kind {
+        TokenKind::TextString => "\x1b[32m",  // green
+        TokenKind::ByteString => "\x1b[33m",  // yellow
+        TokenKind::Number => "\x1b[36m",      // cyan
+        TokenKind::Float => "\x1b[96m",       // bright cyan
+        TokenKind::Keyword => "\x1b[35m",     // magenta
+        TokenKind::Open | TokenKind::Close | TokenKind::Comma | TokenKind::Colon => "\x1b[90m", // dim
+        TokenKind::Marker => "\x1b[90m",
+        TokenKind::Space | TokenKind::Other => return None,
+    };
+    Some(code)
+}
+
+/// Wrap each classified token of `input` in its ANSI colour escape.
+pub fn colorize(input: &str) -> String {
+    let tokens = tokenize(input);
+    let mut out = String::with_capacity(input.len() + input.len() / 4);
+    for (idx, tok) in tokens.iter().enumerate() {
+        // A number directly followed by `(` is a tag number, e.g. `32(...)`.
+        let is_tag = matches!(tok.kind, TokenKind::Number | TokenKind::Float)
+            && tokens
+                .get(idx + 1)
+                .is_some_and(|n| n.kind == TokenKind::Open && n.text == "(");
+        match ansi(tok.kind, is_tag) {
+            Some(code) => {
+                out.push_str(code);
+                out.push_str(tok.text);
+                out.push_str(RESET);
+            }
+            None => out.push_str(tok.text),
+        }
+    }
+    out
+}
@@ -0,0 +1,531 @@
+//! Native CBOR → Extended Diagnostic Notation decoder.
+//!
+//! This walks the CBOR major types defined in RFC 8949 and emits diagnostic
+//! notation directly, so that we no longer have to shell out to the Ruby
+//! `cbor2diag.rb` gem for the common case.
+
+use anyhow::{anyhow, bail, Result};
+use std::fmt::Write;
+
+/// Decode a complete CBOR item into Extended Diagnostic Notation.
+///
+/// When `embedded` is set, byte strings whose contents are themselves valid
+/// CBOR are decoded recursively and rendered using the `<<...>>` notation,
+/// mirroring the `-e` flag of `cbor2diag.rb`.
+pub fn cbor_to_diag(input: &[u8], embedded: bool) -> Result<String> {
+    let mut dec = Decoder {
+        input,
+        pos: 0,
+        embedded,
+    };
+    let mut out = String::with_capacity(input.len() * 2);
+    dec.item(&mut out)?;
+    if dec.pos != input.len() {
+        bail!("trailing bytes after top-level CBOR item");
+    }
+    Ok(out)
+}
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+    embedded: bool,
+}
+
+impl<'a> Decoder<'a> {
+    fn byte(&mut self) -> Result<u8> {
+        let b = *self
+            .input
+            .get(self.pos)
+            .ok_or_else(|| anyhow!("unexpected end of input"))?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .ok_or_else(|| anyhow!("length overflow"))?;
+        let slice = self
+            .input
+            .get(self.pos..end)
+            .ok_or_else(|| anyhow!("unexpected end of input"))?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    /// Read the argument encoded by the additional-information bits `ai`.
+    /// Returns `None` for the indefinite-length marker (31).
+    fn argument(&mut self, ai: u8) -> Result<Option<u64>> {
+        let val = match ai {
+            0..=23 => u64::from(ai),
+            24 => u64::from(self.byte()?),
+            25 => u64::from(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap())),
+            26 => u64::from(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap())),
+            27 => u64::from_be_bytes(self.bytes(8)?.try_into().unwrap()),
+            31 => return Ok(None),
+            _ => bail!("reserved additional information value {ai}"),
+        };
+        Ok(Some(val))
+    }
+
+    /// Consume a `break` byte (`0xff`) if it is next, reporting whether it was.
+    fn at_break(&mut self) -> Result<bool> {
+        match self.input.get(self.pos) {
+            Some(&0xff) => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(_) => Ok(false),
+            None => bail!("unexpected end of input: missing break"),
+        }
+    }
+
+    fn definite(&mut self, ai: u8) -> Result<u64> {
+        self.argument(ai)?
+            .ok_or_else(|| anyhow!("indefinite length not allowed here"))
+    }
+
+    fn item(&mut self, out: &mut String) -> Result<()> {
+        let ib = self.byte()?;
+        let major = ib >> 5;
+        let ai = ib & 0x1f;
+        match major {
+            0 => write!(out, "{}", self.definite(ai)?)?,
+            1 => write!(out, "{}", -1i128 - i128::from(self.definite(ai)?))?,
+            2 => self.byte_string(ai, out)?,
+            3 => self.text_string(ai, out)?,
+            4 => self.array(ai, out)?,
+            5 => self.map(ai, out)?,
+            6 => {
+                write!(out, "{}(", self.definite(ai)?)?;
+                self.item(out)?;
+                out.push(')');
+            }
+            7 => self.simple_or_float(ai, out)?,
+            _ => unreachable!("major type is only three bits"),
+        }
+        Ok(())
+    }
+
+    fn byte_string(&mut self, ai: u8, out: &mut String) -> Result<()> {
+        match self.argument(ai)? {
+            Some(len) => {
+                let data = self.bytes(len as usize)?;
+                if self.embedded && !data.is_empty() {
+                    if let Ok(inner) = cbor_to_diag(data, self.embedded) {
+                        write!(out, "<<{inner}>>")?;
+                        return Ok(());
+                    }
+                }
+                write_byte_string(out, data);
+            }
+            None => {
+                out.push_str("(_ ");
+                let mut first = true;
+                while !self.at_break()? {
+                    if !first {
+                        out.push_str(", ");
+                    }
+                    first = false;
+                    let ib = self.byte()?;
+                    if ib >> 5 != 2 {
+                        bail!("indefinite-length byte string chunk is not a byte string");
+                    }
+                    let len = self.definite(ib & 0x1f)?;
+                    let data = self.bytes(len as usize)?;
+                    write_byte_string(out, data);
+                }
+                out.push(')');
+            }
+        }
+        Ok(())
+    }
+
+    fn text_string(&mut self, ai: u8, out: &mut String) -> Result<()> {
+        match self.argument(ai)? {
+            Some(len) => {
+                let data = self.bytes(len as usize)?;
+                let s = std::str::from_utf8(data).map_err(|_| anyhow!("invalid UTF-8 in text string"))?;
+                write_text_string(out, s);
+            }
+            None => {
+                out.push_str("(_ ");
+                let mut first = true;
+                while !self.at_break()? {
+                    if !first {
+                        out.push_str(", ");
+                    }
+                    first = false;
+                    let ib = self.byte()?;
+                    if ib >> 5 != 3 {
+                        bail!("indefinite-length text string chunk is not a text string");
+                    }
+                    let len = self.definite(ib & 0x1f)?;
+                    let data = self.bytes(len as usize)?;
+                    let s = std::str::from_utf8(data)
+                        .map_err(|_| anyhow!("invalid UTF-8 in text string"))?;
+                    write_text_string(out, s);
+                }
+                out.push(')');
+            }
+        }
+        Ok(())
+    }
+
+    fn array(&mut self, ai: u8, out: &mut String) -> Result<()> {
+        match self.argument(ai)? {
+            Some(len) => {
+                out.push('[');
+                for i in 0..len {
+                    if i != 0 {
+                        out.push_str(", ");
+                    }
+                    self.item(out)?;
+                }
+                out.push(']');
+            }
+            None => {
+                out.push_str("[_ ");
+                let mut first = true;
+                while !self.at_break()? {
+                    if !first {
+                        out.push_str(", ");
+                    }
+                    first = false;
+                    self.item(out)?;
+                }
+                out.push(']');
+            }
+        }
+        Ok(())
+    }
+
+    fn map(&mut self, ai: u8, out: &mut String) -> Result<()> {
+        match self.argument(ai)? {
+            Some(len) => {
+                out.push('{');
+                for i in 0..len {
+                    if i != 0 {
+                        out.push_str(", ");
+                    }
+                    self.item(out)?;
+                    out.push_str(": ");
+                    self.item(out)?;
+                }
+                out.push('}');
+            }
+            None => {
+                out.push_str("{_ ");
+                let mut first = true;
+                while !self.at_break()? {
+                    if !first {
+                        out.push_str(", ");
+                    }
+                    first = false;
+                    self.item(out)?;
+                    out.push_str(": ");
+                    self.item(out)?;
+                }
+                out.push('}');
+            }
+        }
+        Ok(())
+    }
+
+    fn simple_or_float(&mut self, ai: u8, out: &mut String) -> Result<()> {
+        match ai {
+            20 => out.push_str("false"),
+            21 => out.push_str("true"),
+            22 => out.push_str("null"),
+            23 => out.push_str("undefined"),
+            24 => {
+                let v = self.byte()?;
+                if v < 32 {
+                    bail!("simple value {v} must be encoded in the additional information");
+                }
+                write!(out, "simple({v})")?;
+            }
+            25 => {
+                let bits = u16::from_be_bytes(self.bytes(2)?.try_into().unwrap());
+                write_float(out, half_to_f64(bits));
+            }
+            26 => {
+                let bits = u32::from_be_bytes(self.bytes(4)?.try_into().unwrap());
+                write_float(out, f64::from(f32::from_bits(bits)));
+            }
+            27 => {
+                let bits = u64::from_be_bytes(self.bytes(8)?.try_into().unwrap());
+                write_float(out, f64::from_bits(bits));
+            }
+            31 => bail!("unexpected break outside of an indefinite-length item"),
+            _ => write!(out, "simple({ai})")?,
+        }
+        Ok(())
+    }
+}
+
+/// A contiguous run of bytes together with the CBOR item (or fragment) it
+/// encodes. Produced by [`annotate`] and consumed by the `--hexdump` view.
+pub struct Span {
+    /// Offset of the first byte of this span within the input.
+    pub offset: usize,
+    /// The raw bytes covered by this span.
+    pub bytes: Vec<u8>,
+    /// Nesting depth, for indenting the annotation column.
+    pub depth: usize,
+    /// Human-readable description, e.g. `map(2)` or `uint 42`.
+    pub annotation: String,
+}
+
+/// Walk a complete CBOR item and return a flat list of annotated byte spans,
+/// one per item head, string payload and `break` byte.
+pub fn annotate(input: &[u8]) -> Result<Vec<Span>> {
+    let mut ann = Annotator {
+        input,
+        pos: 0,
+        spans: Vec::new(),
+    };
+    ann.item(0)?;
+    if ann.pos != input.len() {
+        bail!("trailing bytes after top-level CBOR item");
+    }
+    Ok(ann.spans)
+}
+
+struct Annotator<'a> {
+    input: &'a [u8],
+    pos: usize,
+    spans: Vec<Span>,
+}
+
+impl<'a> Annotator<'a> {
+    fn byte(&mut self) -> Result<u8> {
+        let b = *self
+            .input
+            .get(self.pos)
+            .ok_or_else(|| anyhow!("unexpected end of input"))?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .ok_or_else(|| anyhow!("length overflow"))?;
+        let slice = self
+            .input
+            .get(self.pos..end)
+            .ok_or_else(|| anyhow!("unexpected end of input"))?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn argument(&mut self, ai: u8) -> Result<Option<u64>> {
+        let val = match ai {
+            0..=23 => u64::from(ai),
+            24 => u64::from(self.byte()?),
+            25 => u64::from(u16::from_be_bytes(self.take(2)?.try_into().unwrap())),
+            26 => u64::from(u32::from_be_bytes(self.take(4)?.try_into().unwrap())),
+            27 => u64::from_be_bytes(self.take(8)?.try_into().unwrap()),
+            31 => return Ok(None),
+            _ => bail!("reserved additional information value {ai}"),
+        };
+        Ok(Some(val))
+    }
+
+    fn push(&mut self, start: usize, depth: usize, annotation: String) {
+        self.spans.push(Span {
+            offset: start,
+            bytes: self.input[start..self.pos].to_vec(),
+            depth,
+            annotation,
+        });
+    }
+
+    /// Emit the spans of one complete item at `depth`: its head, followed by
+    /// any string payload, nested items and `break` byte.
+    fn item(&mut self, depth: usize) -> Result<()> {
+        let start = self.pos;
+        let ib = self.byte()?;
+        let major = ib >> 5;
+        let ai = ib & 0x1f;
+        match major {
+            0 => {
+                let n = self.argument(ai)?.ok_or_else(|| anyhow!("bad integer"))?;
+                self.push(start, depth, format!("uint {n}"));
+            }
+            1 => {
+                let n = self.argument(ai)?.ok_or_else(|| anyhow!("bad integer"))?;
+                self.push(start, depth, format!("nint {}", -1i128 - i128::from(n)));
+            }
+            2 | 3 => self.string(ib, start, depth)?,
+            4 => self.sequence(ai, start, depth, '[')?,
+            5 => self.sequence(ai, start, depth, '{')?,
+            6 => {
+                let tag = self.argument(ai)?.ok_or_else(|| anyhow!("bad tag"))?;
+                self.push(start, depth, format!("tag({tag})"));
+                self.item(depth + 1)?;
+            }
+            7 => {
+                let annotation = self.simple_or_float(ai)?;
+                self.push(start, depth, annotation);
+            }
+            _ => unreachable!("major type is only three bits"),
+        }
+        Ok(())
+    }
+
+    fn string(&mut self, ib: u8, start: usize, depth: usize) -> Result<()> {
+        let major = ib >> 5;
+        let ai = ib & 0x1f;
+        let kind = if major == 2 { "bytes" } else { "text" };
+        match self.argument(ai)? {
+            Some(len) => {
+                self.push(start, depth, format!("{kind}({len})"));
+                let content_start = self.pos;
+                let data = self.take(len as usize)?;
+                let mut rendered = String::new();
+                if major == 2 {
+                    write_byte_string(&mut rendered, data);
+                } else {
+                    let s = std::str::from_utf8(data)
+                        .map_err(|_| anyhow!("invalid UTF-8 in text string"))?;
+                    write_text_string(&mut rendered, s);
+                }
+                self.push(content_start, depth + 1, rendered);
+            }
+            None => {
+                self.push(start, depth, format!("{kind}(*)"));
+                loop {
+                    let chunk_start = self.pos;
+                    if self.byte()? == 0xff {
+                        self.push(chunk_start, depth + 1, "break".to_string());
+                        break;
+                    }
+                    self.pos = chunk_start; // rewind; re-read as a definite chunk
+                    let ib = self.byte()?;
+                    self.string(ib, chunk_start, depth + 1)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn sequence(&mut self, ai: u8, start: usize, depth: usize, open: char) -> Result<()> {
+        let pairs = open == '{';
+        let kind = if pairs { "map" } else { "array" };
+        match self.argument(ai)? {
+            Some(len) => {
+                self.push(start, depth, format!("{kind}({len})"));
+                for _ in 0..len {
+                    self.item(depth + 1)?;
+                    if pairs {
+                        self.item(depth + 1)?;
+                    }
+                }
+            }
+            None => {
+                self.push(start, depth, format!("{kind}(*)"));
+                loop {
+                    let brk = self.pos;
+                    if self.byte()? == 0xff {
+                        self.push(brk, depth + 1, "break".to_string());
+                        break;
+                    }
+                    self.pos = brk;
+                    self.item(depth + 1)?;
+                    if pairs {
+                        self.item(depth + 1)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn simple_or_float(&mut self, ai: u8) -> Result<String> {
+        Ok(match ai {
+            20 => "false".to_string(),
+            21 => "true".to_string(),
+            22 => "null".to_string(),
+            23 => "undefined".to_string(),
+            24 => {
+                let v = self.byte()?;
+                format!("simple({v})")
+            }
+            25 => {
+                let bits = u16::from_be_bytes(self.take(2)?.try_into().unwrap());
+                float_annotation(half_to_f64(bits))
+            }
+            26 => {
+                let bits = u32::from_be_bytes(self.take(4)?.try_into().unwrap());
+                float_annotation(f64::from(f32::from_bits(bits)))
+            }
+            27 => {
+                let bits = u64::from_be_bytes(self.take(8)?.try_into().unwrap());
+                float_annotation(f64::from_bits(bits))
+            }
+            31 => bail!("unexpected break outside of an indefinite-length item"),
+            _ => format!("simple({ai})"),
+        })
+    }
+}
+
+fn float_annotation(val: f64) -> String {
+    let mut s = String::from("float ");
+    write_float(&mut s, val);
+    s
+}
+
+fn write_byte_string(out: &mut String, data: &[u8]) {
+    out.push_str("h'");
+    for b in data {
+        write!(out, "{b:02x}").unwrap();
+    }
+    out.push('\'');
+}
+
+fn write_text_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_float(out: &mut String, val: f64) {
+    if val.is_nan() {
+        out.push_str("NaN");
+    } else if val.is_infinite() {
+        out.push_str(if val < 0.0 { "-Infinity" } else { "Infinity" });
+    } else {
+        // The `Debug` formatter always renders a decimal point or an exponent,
+        // keeping floats visually distinct from integers (`1.0` rather than
+        // `1`, `1e300` for large magnitudes).
+        write!(out, "{val:?}").unwrap();
+    }
+}
+
+/// Convert an IEEE 754 half-precision bit pattern into an `f64`.
+fn half_to_f64(half: u16) -> f64 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = (half >> 10) & 0x1f;
+    let mant = f64::from(half & 0x03ff);
+    let val = if exp == 0 {
+        mant * 2f64.powi(-24)
+    } else if exp != 31 {
+        (mant + 1024.0) * 2f64.powi(i32::from(exp) - 25)
+    } else if mant == 0.0 {
+        f64::INFINITY
+    } else {
+        f64::NAN
+    };
+    sign * val
+}
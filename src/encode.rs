@@ -0,0 +1,467 @@
+//! The inverse of the decode path: parse Extended Diagnostic Notation into a
+//! value tree and encode it back into canonical CBOR.
+//!
+//! Encoding follows the core deterministic rules of RFC 8949 §4.2: integers,
+//! string lengths and tags use the shortest-form argument, floats use the
+//! shortest representation that round-trips, and map keys are emitted in the
+//! bytewise lexicographic order of their encodings.
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::token::{tokenize, Token, TokenKind};
+
+/// A decoded CBOR value, the intermediate representation between diagnostic
+/// notation and canonical CBOR bytes.
+enum Value {
+    Uint(u64),
+    /// A negative integer stored as `n`, encoding the value `-1 - n`.
+    Nint(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Tag(u64, Box<Value>),
+    Simple(u8),
+    Bool(bool),
+    Null,
+    Undefined,
+    Float(f64),
+}
+
+/// Parse diagnostic notation and encode it into canonical CBOR bytes.
+pub fn encode_diag(input: &str) -> Result<Vec<u8>> {
+    let tokens: Vec<Token> = tokenize(input)
+        .into_iter()
+        .filter(|t| t.kind != TokenKind::Space)
+        .collect();
+    let mut parser = Parser { tokens, pos: 0 };
+    let value = parser.value()?;
+    if parser.pos != parser.tokens.len() {
+        bail!("trailing tokens after top-level value");
+    }
+    let mut out = Vec::new();
+    encode_value(&value, &mut out);
+    Ok(out)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Result<Token<'a>> {
+        self.tokens
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| anyhow!("unexpected end of input"))
+    }
+
+    fn peek_at(&self, n: usize) -> Option<Token<'a>> {
+        self.tokens.get(self.pos + n).copied()
+    }
+
+    fn advance(&mut self) -> Result<Token<'a>> {
+        let tok = self.peek()?;
+        self.pos += 1;
+        Ok(tok)
+    }
+
+    fn expect(&mut self, kind: TokenKind, text: &str) -> Result<()> {
+        let tok = self.advance()?;
+        if tok.kind != kind || tok.text != text {
+            bail!("expected `{text}`, found `{}`", tok.text);
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let tok = self.peek()?;
+        match tok.kind {
+            TokenKind::Number => {
+                // `N(...)` is a tag, otherwise a plain integer.
+                if matches!(self.peek_at(1), Some(t) if t.kind == TokenKind::Open && t.text == "(") {
+                    let tag: u64 = tok
+                        .text
+                        .parse()
+                        .map_err(|_| anyhow!("invalid tag number `{}`", tok.text))?;
+                    self.pos += 2; // number and `(`
+                    let inner = self.value()?;
+                    self.expect(TokenKind::Close, ")")?;
+                    Ok(Value::Tag(tag, Box::new(inner)))
+                } else {
+                    self.pos += 1;
+                    parse_integer(tok.text)
+                }
+            }
+            TokenKind::Float => {
+                self.pos += 1;
+                Ok(Value::Float(
+                    tok.text
+                        .parse()
+                        .map_err(|_| anyhow!("invalid float `{}`", tok.text))?,
+                ))
+            }
+            TokenKind::TextString => {
+                self.pos += 1;
+                Ok(Value::Text(unescape_text(tok.text)?))
+            }
+            TokenKind::ByteString => {
+                self.pos += 1;
+                Ok(Value::Bytes(parse_byte_string(tok.text)?))
+            }
+            TokenKind::Keyword => {
+                self.pos += 1;
+                match tok.text {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "null" => Ok(Value::Null),
+                    "undefined" => Ok(Value::Undefined),
+                    "NaN" => Ok(Value::Float(f64::NAN)),
+                    "Infinity" => Ok(Value::Float(f64::INFINITY)),
+                    "simple" => {
+                        self.expect(TokenKind::Open, "(")?;
+                        let n = self.advance()?;
+                        let v: u8 = n
+                            .text
+                            .parse()
+                            .map_err(|_| anyhow!("invalid simple value `{}`", n.text))?;
+                        self.expect(TokenKind::Close, ")")?;
+                        Ok(Value::Simple(v))
+                    }
+                    other => bail!("unknown keyword `{other}`"),
+                }
+            }
+            // `-Infinity` tokenizes as a bare `-` followed by the identifier.
+            TokenKind::Other if tok.text == "-" => {
+                if matches!(self.peek_at(1), Some(t) if t.kind == TokenKind::Keyword && t.text == "Infinity")
+                {
+                    self.pos += 2;
+                    Ok(Value::Float(f64::NEG_INFINITY))
+                } else {
+                    bail!("unexpected `-`");
+                }
+            }
+            TokenKind::Open if tok.text == "[" => {
+                self.pos += 1;
+                self.skip_marker();
+                let mut items = Vec::new();
+                while !self.at_close("]") {
+                    items.push(self.value()?);
+                    if !self.take_comma() {
+                        break;
+                    }
+                }
+                self.expect(TokenKind::Close, "]")?;
+                Ok(Value::Array(items))
+            }
+            TokenKind::Open if tok.text == "{" => {
+                self.pos += 1;
+                self.skip_marker();
+                let mut pairs = Vec::new();
+                while !self.at_close("}") {
+                    let key = self.value()?;
+                    self.expect(TokenKind::Colon, ":")?;
+                    let val = self.value()?;
+                    pairs.push((key, val));
+                    if !self.take_comma() {
+                        break;
+                    }
+                }
+                self.expect(TokenKind::Close, "}")?;
+                Ok(Value::Map(pairs))
+            }
+            // `(_ h'..', h'..')` — an indefinite-length string; concatenate the
+            // chunks and re-emit as a single canonical definite-length string.
+            TokenKind::Open if tok.text == "(" => {
+                self.pos += 1;
+                self.expect(TokenKind::Marker, "_")?;
+                let mut bytes: Option<Vec<u8>> = None;
+                let mut text: Option<String> = None;
+                while !self.at_close(")") {
+                    match self.value()? {
+                        Value::Bytes(b) => bytes.get_or_insert_with(Vec::new).extend(b),
+                        Value::Text(t) => text.get_or_insert_with(String::new).push_str(&t),
+                        _ => bail!("indefinite-length string chunk must be a string"),
+                    }
+                    if !self.take_comma() {
+                        break;
+                    }
+                }
+                self.expect(TokenKind::Close, ")")?;
+                match (bytes, text) {
+                    (Some(b), None) => Ok(Value::Bytes(b)),
+                    (None, Some(t)) => Ok(Value::Text(t)),
+                    (None, None) => Ok(Value::Bytes(Vec::new())),
+                    _ => bail!("indefinite-length string mixes byte and text chunks"),
+                }
+            }
+            _ => bail!("unexpected token `{}`", tok.text),
+        }
+    }
+
+    fn skip_marker(&mut self) {
+        if matches!(self.peek(), Ok(t) if t.kind == TokenKind::Marker) {
+            self.pos += 1;
+        }
+    }
+
+    fn take_comma(&mut self) -> bool {
+        if matches!(self.peek(), Ok(t) if t.kind == TokenKind::Comma) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn at_close(&self, text: &str) -> bool {
+        matches!(self.peek(), Ok(t) if t.kind == TokenKind::Close && t.text == text)
+    }
+}
+
+fn parse_integer(text: &str) -> Result<Value> {
+    let n: i128 = text
+        .parse()
+        .map_err(|_| anyhow!("invalid integer `{text}`"))?;
+    if n >= 0 {
+        u64::try_from(n)
+            .map(Value::Uint)
+            .map_err(|_| anyhow!("integer `{text}` out of range"))
+    } else {
+        u64::try_from(-1 - n)
+            .map(Value::Nint)
+            .map_err(|_| anyhow!("integer `{text}` out of range"))
+    }
+}
+
+fn parse_byte_string(text: &str) -> Result<Vec<u8>> {
+    // `text` looks like `h'<hex>'`.
+    let inner = text
+        .strip_prefix("h'")
+        .and_then(|s| s.strip_suffix('\''))
+        .ok_or_else(|| anyhow!("malformed byte string `{text}`"))?;
+    hex::decode(inner).map_err(|_| anyhow!("invalid hex in byte string `{text}`"))
+}
+
+fn unescape_text(text: &str) -> Result<String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| anyhow!("malformed text string `{text}`"))?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some(escaped) => out.push(escaped),
+                None => bail!("dangling escape in text string"),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    Ok(out)
+}
+
+fn encode_value(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Uint(n) => write_head(out, 0, *n),
+        Value::Nint(n) => write_head(out, 1, *n),
+        Value::Bytes(b) => {
+            write_head(out, 2, b.len() as u64);
+            out.extend_from_slice(b);
+        }
+        Value::Text(s) => {
+            write_head(out, 3, s.len() as u64);
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Array(items) => {
+            write_head(out, 4, items.len() as u64);
+            for item in items {
+                encode_value(item, out);
+            }
+        }
+        Value::Map(pairs) => {
+            write_head(out, 5, pairs.len() as u64);
+            // Canonical ordering: sort by the bytewise encoding of each key.
+            let mut encoded: Vec<(Vec<u8>, Vec<u8>)> = pairs
+                .iter()
+                .map(|(k, v)| {
+                    let mut key = Vec::new();
+                    encode_value(k, &mut key);
+                    let mut val = Vec::new();
+                    encode_value(v, &mut val);
+                    (key, val)
+                })
+                .collect();
+            encoded.sort_by(|a, b| a.0.cmp(&b.0));
+            for (key, val) in encoded {
+                out.extend_from_slice(&key);
+                out.extend_from_slice(&val);
+            }
+        }
+        Value::Tag(tag, inner) => {
+            write_head(out, 6, *tag);
+            encode_value(inner, out);
+        }
+        Value::Simple(n) => {
+            if *n < 24 {
+                out.push(0xe0 | n);
+            } else {
+                out.push(0xf8);
+                out.push(*n);
+            }
+        }
+        Value::Bool(false) => out.push(0xf4),
+        Value::Bool(true) => out.push(0xf5),
+        Value::Null => out.push(0xf6),
+        Value::Undefined => out.push(0xf7),
+        Value::Float(f) => encode_float(out, *f),
+    }
+}
+
+fn write_head(out: &mut Vec<u8>, major: u8, arg: u64) {
+    let mt = major << 5;
+    if arg < 24 {
+        out.push(mt | arg as u8);
+    } else if arg <= u64::from(u8::MAX) {
+        out.push(mt | 24);
+        out.push(arg as u8);
+    } else if arg <= u64::from(u16::MAX) {
+        out.push(mt | 25);
+        out.extend_from_slice(&(arg as u16).to_be_bytes());
+    } else if arg <= u64::from(u32::MAX) {
+        out.push(mt | 26);
+        out.extend_from_slice(&(arg as u32).to_be_bytes());
+    } else {
+        out.push(mt | 27);
+        out.extend_from_slice(&arg.to_be_bytes());
+    }
+}
+
+fn encode_float(out: &mut Vec<u8>, f: f64) {
+    if f.is_nan() {
+        out.extend_from_slice(&[0xf9, 0x7e, 0x00]);
+        return;
+    }
+    if let Some(half) = f64_to_half(f) {
+        out.push(0xf9);
+        out.extend_from_slice(&half.to_be_bytes());
+        return;
+    }
+    let single = f as f32;
+    if f64::from(single) == f {
+        out.push(0xfa);
+        out.extend_from_slice(&single.to_bits().to_be_bytes());
+        return;
+    }
+    out.push(0xfb);
+    out.extend_from_slice(&f.to_bits().to_be_bytes());
+}
+
+/// Return the half-precision bit pattern for `value` iff it is exactly
+/// representable as an IEEE 754 half.
+fn f64_to_half(value: f64) -> Option<u16> {
+    if value.is_infinite() {
+        return Some(if value < 0.0 { 0xfc00 } else { 0x7c00 });
+    }
+    let single = value as f32;
+    if f64::from(single) != value {
+        return None;
+    }
+    let bits = single.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mant = bits & 0x007f_ffff;
+    if exp == 0 && mant == 0 {
+        return Some(sign); // signed zero
+    }
+    let unbiased = exp - 127;
+    if !(-24..=15).contains(&unbiased) {
+        return None;
+    }
+    if unbiased >= -14 {
+        // Normalized: the low 13 bits of the single mantissa must be zero.
+        if mant & 0x1fff != 0 {
+            return None;
+        }
+        let half_exp = (unbiased + 15) as u16;
+        let half_mant = (mant >> 13) as u16;
+        Some(sign | (half_exp << 10) | half_mant)
+    } else {
+        // Subnormal half: mant_half = mant_full >> (-unbiased - 1).
+        let mant_full = mant | 0x0080_0000;
+        let shift = (-unbiased - 1) as u32;
+        if mant_full & ((1 << shift) - 1) != 0 {
+            return None;
+        }
+        Some(sign | (mant_full >> shift) as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode_diag;
+    use crate::decode::cbor_to_diag;
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// Canonical CBOR should survive a hex -> diagnostic -> hex round trip
+    /// unchanged, exercising the shortest-form integer, half-float and
+    /// deterministic map-ordering logic.
+    fn assert_roundtrip(h: &str) {
+        let diag = cbor_to_diag(&unhex(h), false).unwrap();
+        assert_eq!(hex(&encode_diag(&diag).unwrap()), h, "diag: {diag}");
+    }
+
+    #[test]
+    fn roundtrip_integers() {
+        assert_roundtrip("00"); // 0
+        assert_roundtrip("182a"); // 42
+        assert_roundtrip("1903e8"); // 1000
+        assert_roundtrip("20"); // -1
+        assert_roundtrip("3903e7"); // -1000
+    }
+
+    #[test]
+    fn roundtrip_floats() {
+        assert_roundtrip("f93c00"); // 1.0 as half
+        assert_roundtrip("f90000"); // 0.0 as half
+        assert_roundtrip("fa47c35000"); // 100000.0 as single
+        assert_roundtrip("fb7e37e43c8800759c"); // 1e300 as double
+    }
+
+    #[test]
+    fn roundtrip_strings_and_tags() {
+        assert_roundtrip("63666f6f"); // "foo"
+        assert_roundtrip("43010203"); // h'010203'
+        assert_roundtrip("c074323031332d30332d32315432303a30343a30305a"); // 0("2013-03-21T20:04:00Z")
+    }
+
+    #[test]
+    fn roundtrip_simple_and_container() {
+        assert_roundtrip("f4"); // false
+        assert_roundtrip("f6"); // null
+        assert_roundtrip("f7"); // undefined
+        assert_roundtrip("e0"); // simple(0)
+        assert_roundtrip("83010203"); // [1, 2, 3]
+    }
+
+    #[test]
+    fn map_keys_sorted_canonically() {
+        // Keys given out of order must come back in canonical (bytewise) order:
+        // 1, 2, 10 -> 01, 02, 0a.
+        let bytes = encode_diag("{10: 0, 1: 0, 2: 0}").unwrap();
+        assert_eq!(hex(&bytes), "a3010002000a00");
+    }
+}
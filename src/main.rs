@@ -5,6 +5,14 @@ use std::io;
 use std::io::{Read, Write as IoWrite};
 use std::process::{Command, Stdio};
 
+mod color;
+mod decode;
+mod encode;
+mod token;
+
+use color::ColorChoice;
+use token::{tokenize, TokenKind};
+
 /// One indentation level is by default this much spaces.
 const SPACE_COUNT: usize = 2;
 
@@ -28,6 +36,25 @@ struct CliInput {
     #[arg(short, long)]
     diag: bool,
 
+    /// Encode diagnostic notation into canonical CBOR instead of decoding,{n}
+    /// printing the result as hexadecimal (or see --raw).
+    #[arg(long)]
+    encode: bool,
+
+    /// With --encode, write the raw CBOR bytes to stdout instead of hex.
+    #[arg(long, requires = "encode")]
+    raw: bool,
+
+    /// Emit an annotated hex dump (offset, hex bytes, decoded meaning){n}
+    /// instead of the diagnostic pretty-print. Requires hexadecimal input.
+    #[arg(long)]
+    hexdump: bool,
+
+    /// Colorize the output: auto (only on a TTY), always, or never.{n}
+    /// The NO_COLOR environment variable disables colors when set.
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
     /// Data to act on, either hexadecimal or diagnostic. If absent, stdin is read.{n}
     /// If neither --hex or --diag is given, the input is parsed as hexadecimal.{n}
     /// If that works, the result is passed through cbor2diag.rb and then acted upon.{n}
@@ -51,6 +78,26 @@ fn main() -> Result<()> {
         bail!("no input received, pass input either via stdin or command-line argument");
     }
 
+    // Reverse direction: diagnostic notation in, canonical CBOR out.
+    if cli_input.encode {
+        let cbor = encode::encode_diag(&input_raw)?;
+        if cli_input.raw {
+            io::stdout().write_all(&cbor)?;
+        } else {
+            println!("{}", hex::encode(&cbor));
+        }
+        return Ok(());
+    }
+
+    // The hex dump works on the raw CBOR bytes rather than on diagnostic
+    // notation, so it has its own short-circuiting output path.
+    if cli_input.hexdump {
+        let cbor = hex::decode(input_raw.trim())
+            .context("--hexdump requires hexadecimal CBOR input")?;
+        print!("{}", hexdump(cbor.as_slice())?);
+        return Ok(());
+    }
+
     // Determine the input for the pretty printing as specified by the options
     let input: Vec<u8> = if cli_input.hex {
         cbor2diag(
@@ -64,7 +111,12 @@ fn main() -> Result<()> {
     };
 
     // Do our thing
-    println!("{}", pretty_print(input.as_slice(), cli_input.indent));
+    let pretty = pretty_print(input.as_slice(), cli_input.indent);
+    if color::should_color(cli_input.color) {
+        println!("{}", color::colorize(&pretty));
+    } else {
+        println!("{pretty}");
+    }
     Ok(())
 }
 
@@ -76,13 +128,17 @@ fn try_hex_cbor2diag(input_raw: String, embedded: bool) -> Result<Vec<u8>> {
     Ok(input)
 }
 
-const NO_CBOR2DIAG_ERR: &str = "failed to locate cbor2diag.rb.
-Ensure cbor2diag.rb is installed (using \"gem install cbor-diag\") and present in your $PATH,
-or input diagnostic CBOR instead (e.g. using https://https://cbor.me).";
-
 fn cbor2diag(input: Vec<u8>, embedded: bool) -> Result<Vec<u8>> {
-    let cbor2diag = which::which("cbor2diag.rb").context(NO_CBOR2DIAG_ERR)?;
+    // Prefer the reference cbor2diag.rb implementation when it is available,
+    // falling back to our native decoder otherwise so the tool stays
+    // self-contained even without Ruby on $PATH.
+    match which::which("cbor2diag.rb") {
+        Ok(path) => cbor2diag_ruby(path, input, embedded),
+        Err(_) => Ok(decode::cbor_to_diag(&input, embedded)?.into_bytes()),
+    }
+}
 
+fn cbor2diag_ruby(cbor2diag: std::path::PathBuf, input: Vec<u8>, embedded: bool) -> Result<Vec<u8>> {
     let args: &[&str] = if embedded { &["-e"] } else { &[] };
     let mut process = Command::new(cbor2diag)
         .args(args)
@@ -108,89 +164,112 @@ fn cbor2diag(input: Vec<u8>, embedded: bool) -> Result<Vec<u8>> {
     }
 }
 
+fn hexdump(cbor: &[u8]) -> Result<String> {
+    let spans = decode::annotate(cbor)?;
+    let mut out = String::new();
+    for span in spans {
+        let indent = "  ".repeat(span.depth);
+        if span.bytes.is_empty() {
+            writeln!(out, "{:06x}  {:<23}  {indent}{}", span.offset, "", span.annotation)?;
+            continue;
+        }
+        // Wrap long payloads onto continuation rows of eight bytes each; the
+        // first row carries the annotation.
+        for (chunk_idx, chunk) in span.bytes.chunks(8).enumerate() {
+            let offset = span.offset + chunk_idx * 8;
+            let hex = chunk
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            if chunk_idx == 0 {
+                writeln!(out, "{offset:06x}  {hex:<23}  {indent}{}", span.annotation)?;
+            } else {
+                writeln!(out, "{offset:06x}  {hex:<23}")?;
+            }
+        }
+    }
+    Ok(out)
+}
+
 fn pretty_print(input: &[u8], space_count: usize) -> String {
+    // The formatter works on the token stream rather than on raw characters so
+    // that brackets, commas or `_` markers appearing inside string and byte
+    // string literals are never mistaken for structure.
+    let diag = String::from_utf8_lossy(input);
+    let tokens: Vec<_> = tokenize(&diag)
+        .into_iter()
+        .filter(|t| t.kind != TokenKind::Space)
+        .collect();
+
     // Specify a capacity to try to avoid reallocation. The factor 2 is a little arbitrary
     // but should suffice in most cases.
     let mut output = String::with_capacity(input.len() * 2);
-
-    let mut in_str = false;
     let mut indent_count = 0;
+    // Stack of the currently-open brackets. Only `{`/`[` introduce indentation;
+    // a `(` (tag syntax, indefinite-length string chunks) is a
+    // grouping context whose contents stay inline, so commas inside it do not
+    // break onto a new line and the `_` marker keeps its trailing space.
+    let mut stack: Vec<&str> = Vec::new();
 
-    for idx in 0..input.len() {
-        let c = input[idx] as char;
-        let prev = idx.checked_sub(1).map(|i| input[i] as char);
-        let next = input.get(idx + 1).map(|b| *b as char);
-
-        if c == '\"' && prev.map_or(true, |ch| ch != '\\') {
-            in_str = !in_str;
-        }
-
-        if in_str {
-            // If we're in a string, always just print it
-            write_char(&mut output, c, in_str);
-        } else {
-            process_char(
-                c,
-                &mut output,
-                &mut indent_count,
-                space_count,
-                prev,
-                next,
-                in_str,
-            );
+    let mut i = 0;
+    while i < tokens.len() {
+        let tok = tokens[i];
+        match tok.kind {
+            TokenKind::Open if tok.text == "{" || tok.text == "[" => {
+                stack.push(tok.text);
+                output.push_str(tok.text);
+                // Keep an indefinite-length marker on the opening line: `[_`.
+                if matches!(tokens.get(i + 1), Some(t) if t.kind == TokenKind::Marker) {
+                    output.push('_');
+                    i += 1;
+                }
+                indent_count += 1;
+                let empty = matches!(tokens.get(i + 1), Some(t) if t.kind == TokenKind::Close);
+                if !empty {
+                    newline(&mut output, indent_count, space_count);
+                }
+            }
+            TokenKind::Open => {
+                // `(` — inline grouping context.
+                stack.push(tok.text);
+                output.push_str(tok.text);
+            }
+            TokenKind::Close if tok.text == "}" || tok.text == "]" => {
+                stack.pop();
+                indent_count = indent_count.saturating_sub(1);
+                let empty = i.checked_sub(1).and_then(|p| tokens.get(p)).is_some_and(|t| {
+                    t.kind == TokenKind::Open || t.kind == TokenKind::Marker
+                });
+                if !empty {
+                    newline(&mut output, indent_count, space_count);
+                }
+                output.push_str(tok.text);
+            }
+            TokenKind::Close => {
+                // `)`
+                stack.pop();
+                output.push_str(tok.text);
+            }
+            TokenKind::Comma => {
+                // Inside a `(` group the chunk/argument list stays on one line.
+                if stack.last() == Some(&"(") {
+                    output.push_str(", ");
+                } else {
+                    output.push(',');
+                    newline(&mut output, indent_count, space_count);
+                }
+            }
+            TokenKind::Marker if stack.last() == Some(&"(") => output.push_str("_ "),
+            TokenKind::Colon => output.push_str(": "),
+            _ => output.push_str(tok.text),
         }
+        i += 1;
     }
 
     output
 }
 
-fn process_char(
-    c: char,
-    output: &mut String,
-    indent_count: &mut usize,
-    space_count: usize,
-    prev: Option<char>,
-    next: Option<char>,
-    in_str: bool,
-) {
-    if is_open(c) {
-        write_char(output, c, in_str);
-        *indent_count += 1;
-        if next.map_or(false, |ch| !is_close(ch)) {
-            newline(output, *indent_count, space_count);
-        }
-    } else if is_close(c) {
-        *indent_count -= 1;
-        if prev.map_or(false, |ch| !is_open(ch)) {
-            newline(output, *indent_count, space_count);
-        }
-        write_char(output, c, in_str);
-    } else if c == ',' {
-        write_char(output, c, in_str);
-        newline(output, *indent_count, space_count);
-    } else {
-        write_char(output, c, in_str);
-    }
-}
-
-fn is_open(c: char) -> bool {
-    c == '{' || c == '['
-}
-
-fn is_close(c: char) -> bool {
-    c == '}' || c == ']'
-}
-
-fn write_char(output: &mut String, c: char, in_str: bool) {
-    if !in_str && c == ' ' {
-        return;
-    }
-    output.write_char(c).unwrap();
-    if !in_str && c == ':' {
-        output.write_char(' ').unwrap();
-    }
-}
-
 fn newline(output: &mut String, indent_count: usize, space_count: usize) {
     output.write_char('\n').unwrap();
     output
@@ -0,0 +1,124 @@
+//! A small tokenizer over Extended Diagnostic Notation.
+//!
+//! A plain char-by-char scan cannot tell a `[` that opens an array from one
+//! that merely appears inside a byte string, so anything that needs to reason
+//! about token kinds (syntax colouring, indentation in `pretty_print`, the
+//! `--encode` parser) works off the token stream produced here instead.
+
+/// The kind of a single diagnostic-notation token.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenKind {
+    /// A double-quoted text string, including the quotes.
+    TextString,
+    /// A byte string literal in the `h'...'` form.
+    ByteString,
+    /// An integer.
+    Number,
+    /// A floating point number (contains a `.` or an exponent).
+    Float,
+    /// An identifier such as `true`, `false`, `null`, `undefined` or `simple`.
+    Keyword,
+    /// An opening bracket: `{`, `[` or `(`.
+    Open,
+    /// A closing bracket: `}`, `]` or `)`.
+    Close,
+    /// An item separator `,`.
+    Comma,
+    /// A key/value separator `:`.
+    Colon,
+    /// The indefinite-length marker `_`.
+    Marker,
+    /// Runs of whitespace.
+    Space,
+    /// Anything else (e.g. the `<<`/`>>` embedded-CBOR delimiters).
+    Other,
+}
+
+/// A token together with the source slice it was sliced from.
+#[derive(Clone, Copy, Debug)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+}
+
+/// Split diagnostic notation into tokens. The concatenation of the token texts
+/// always reproduces the input exactly, so this is lossless.
+pub fn tokenize(input: &str) -> Vec<Token<'_>> {
+    let bytes = input.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i] as char;
+        let kind = if c.is_ascii_whitespace() {
+            while i < bytes.len() && (bytes[i] as char).is_ascii_whitespace() {
+                i += 1;
+            }
+            TokenKind::Space
+        } else if c == '"' {
+            i += 1;
+            while i < bytes.len() {
+                let ch = bytes[i];
+                i += 1;
+                if ch == b'\\' {
+                    i = (i + 1).min(bytes.len()); // skip the escaped character
+                } else if ch == b'"' {
+                    break;
+                }
+            }
+            TokenKind::TextString
+        } else if c == 'h' && bytes.get(i + 1) == Some(&b'\'') {
+            i += 2;
+            while i < bytes.len() && bytes[i] != b'\'' {
+                i += 1;
+            }
+            if i < bytes.len() {
+                i += 1; // closing quote
+            }
+            TokenKind::ByteString
+        } else if c.is_ascii_digit()
+            || (c == '-' && matches!(bytes.get(i + 1), Some(b) if b.is_ascii_digit()))
+        {
+            i += 1;
+            let mut float = false;
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'0'..=b'9' | b'+' | b'-' => i += 1,
+                    b'.' | b'e' | b'E' => {
+                        float = true;
+                        i += 1;
+                    }
+                    _ => break,
+                }
+            }
+            if float {
+                TokenKind::Float
+            } else {
+                TokenKind::Number
+            }
+        } else if c.is_ascii_alphabetic() {
+            while i < bytes.len()
+                && ((bytes[i] as char).is_ascii_alphanumeric() || bytes[i] == b'_')
+            {
+                i += 1;
+            }
+            TokenKind::Keyword
+        } else {
+            // Step over a whole character so a non-ASCII one stays in one token.
+            i += input[start..].chars().next().map_or(1, char::len_utf8);
+            match c {
+                '{' | '[' | '(' => TokenKind::Open,
+                '}' | ']' | ')' => TokenKind::Close,
+                ',' => TokenKind::Comma,
+                ':' => TokenKind::Colon,
+                '_' => TokenKind::Marker,
+                _ => TokenKind::Other,
+            }
+        };
+        tokens.push(Token {
+            kind,
+            text: &input[start..i],
+        });
+    }
+    tokens
+}